@@ -1,6 +1,9 @@
-use scrypto::jubjub::{JubjubEngine, FixedGenerators};
+use scrypto::jubjub::{JubjubEngine, FixedGenerators, edwards};
 use crate::elgamal::Ciphertext;
 use crate::EncryptionKey;
+use pairing::Field;
+use rand::Rng;
+use std::io::{self, Write};
 use std::marker::PhantomData;
 
 #[derive(Clone)]
@@ -17,6 +20,9 @@ pub struct MultiCiphertexts<E: JubjubEngine, CA: PrivacyConfing> {
     sender: Ciphertext<E>,
     recipient: Ciphertext<E>,
     decoys: Option<Vec<Ciphertext<E>>>,
+    /// Index of the sender/recipient pair within the decoy set, so the
+    /// anonymity set can be reassembled in the order the circuit expects.
+    position: Option<usize>,
     fee: Ciphertext<E>,
     _marker: PhantomData<CA>,
 }
@@ -33,6 +39,53 @@ impl<E: JubjubEngine, CA: PrivacyConfing> MultiCiphertexts<E, CA> {
     pub fn get_fee(&self) -> &Ciphertext<E> {
         &self.fee
     }
+
+    /// The decoy ciphertexts of an anonymity set, or an empty slice for
+    /// `Confidential` transfers which carry no decoys.
+    pub fn get_decoys(&self) -> &[Ciphertext<E>] {
+        match &self.decoys {
+            Some(decoys) => decoys,
+            None => &[],
+        }
+    }
+
+    /// Index of the sender/recipient pair within the decoy set.
+    pub fn get_position(&self) -> Option<usize> {
+        self.position
+    }
+
+    /// The full anonymity set in on-chain order: every decoy ciphertext with
+    /// `sender` and `recipient` spliced in at `position`, so nothing about
+    /// the returned order distinguishes the real slots from the decoys.
+    /// Returns `None` for `Confidential` transfers, which carry no decoys.
+    pub fn get_anonymous_set(&self) -> Option<Vec<Ciphertext<E>>> {
+        let decoys = self.decoys.as_ref()?;
+        let position = self.position?;
+
+        let mut set = Vec::with_capacity(decoys.len() + 2);
+        set.extend_from_slice(&decoys[..position]);
+        set.push(self.sender.clone());
+        set.push(self.recipient.clone());
+        set.extend_from_slice(&decoys[position..]);
+
+        Some(set)
+    }
+
+    /// Deterministically serializes the on-chain ciphertext set: the
+    /// anonymity set in `get_anonymous_set` order for `Anonymous` transfers,
+    /// or just `sender` then `recipient` for `Confidential` ones which have
+    /// no decoys to merge with.
+    pub fn write_anonymous_set<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let set = self.get_anonymous_set()
+            .unwrap_or_else(|| vec![self.sender.clone(), self.recipient.clone()]);
+
+        for ciphertext in &set {
+            ciphertext.get_left().write(&mut writer)?;
+            ciphertext.get_right().write(&mut writer)?;
+        }
+
+        Ok(())
+    }
 }
 
 pub trait CiphertextTrait<E: JubjubEngine> {
@@ -103,27 +156,250 @@ impl<E: JubjubEngine> MultiCiphertexts<E, Confidential> {
             sender,
             recipient,
             decoys: None,
+            position: None,
             fee,
             _marker: PhantomData,
         }
     }
 }
 
+/// A lightweight sigma-protocol proof that the sender and recipient
+/// ciphertexts of a `MultiCiphertexts<E, Confidential>` encrypt the same
+/// `amount` under the same `randomness`, without running the full
+/// polynomial-commitment circuit. Proves knowledge of `(m, r)` such that
+/// `sender = (g^r, pk_sender^r + g^m)` and `recipient = (g^r, pk_recipient^r + g^m)`,
+/// with a single Fiat-Shamir challenge binding the `c2_sender` and
+/// `c2_recipient` relations; the shared `c1 = g^r` is checked directly by
+/// comparing `sender.get_left()` and `recipient.get_left()`.
+#[derive(Clone)]
+pub struct EqualityProof<E: JubjubEngine> {
+    a_g: edwards::Point<E, edwards::PrimeOrder>,
+    a_sender: edwards::Point<E, edwards::PrimeOrder>,
+    a_recipient: edwards::Point<E, edwards::PrimeOrder>,
+    z_r: E::Fs,
+    z_m: E::Fs,
+}
+
+impl<E: JubjubEngine> MultiCiphertexts<E, Confidential> {
+    /// The Fiat-Shamir challenge for an [`EqualityProof`], derived from
+    /// every public value the verifier also has on hand.
+    fn equality_challenge(
+        &self,
+        enc_key_sender: &EncryptionKey<E>,
+        enc_key_recipient: &EncryptionKey<E>,
+        a_g: &edwards::Point<E, edwards::PrimeOrder>,
+        a_sender: &edwards::Point<E, edwards::PrimeOrder>,
+        a_recipient: &edwards::Point<E, edwards::PrimeOrder>,
+    ) -> E::Fs {
+        let mut repr = Vec::new();
+        for point in &[
+            self.sender.get_left(),
+            self.sender.get_right(),
+            self.recipient.get_left(),
+            self.recipient.get_right(),
+            enc_key_sender.get_point(),
+            enc_key_recipient.get_point(),
+            a_g,
+            a_sender,
+            a_recipient,
+        ] {
+            point.write(&mut repr).expect("writing to a Vec never fails");
+        }
+
+        E::Fs::to_uniform(&repr).expect("hash output is large enough to reduce into Fs")
+    }
+
+    /// Proves that `self.get_sender()` and `self.get_recipient()` encrypt
+    /// the same `amount` under the same `randomness`, so a third party can
+    /// check this cheaply instead of requiring the expensive SNARK circuit
+    /// for every transfer.
+    pub fn prove_equality<R: Rng>(
+        &self,
+        amount: u32,
+        randomness: &E::Fs,
+        enc_key_sender: &EncryptionKey<E>,
+        enc_key_recipient: &EncryptionKey<E>,
+        params: &E::Params,
+        rng: &mut R,
+    ) -> EqualityProof<E> {
+        let p_g = params.generator(FixedGenerators::NoteCommitmentRandomness);
+
+        let b = E::Fs::rand(rng);
+        let a = E::Fs::rand(rng);
+
+        let a_g = p_g.mul(b, params);
+        let a_sender = enc_key_sender.get_point().mul(b, params).add(&p_g.mul(a, params), params);
+        let a_recipient = enc_key_recipient.get_point().mul(b, params).add(&p_g.mul(a, params), params);
+
+        let e = self.equality_challenge(enc_key_sender, enc_key_recipient, &a_g, &a_sender, &a_recipient);
+
+        let mut z_r = e;
+        z_r.mul_assign(randomness);
+        z_r.add_assign(&b);
+
+        let amount_fs = E::Fs::from_str(&amount.to_string())
+            .expect("u32 amounts always fit in the scalar field");
+        let mut z_m = e;
+        z_m.mul_assign(&amount_fs);
+        z_m.add_assign(&a);
+
+        EqualityProof { a_g, a_sender, a_recipient, z_r, z_m }
+    }
+
+    /// Verifies an [`EqualityProof`] produced by [`Self::prove_equality`].
+    pub fn verify_equality(
+        &self,
+        proof: &EqualityProof<E>,
+        enc_key_sender: &EncryptionKey<E>,
+        enc_key_recipient: &EncryptionKey<E>,
+        params: &E::Params,
+    ) -> bool {
+        // `c1_sender` and `c1_recipient` both claim to be `g^r` for the same
+        // `r`; checking them equal up front is free and closes the gap where
+        // a `recipient` ciphertext encrypted under different randomness
+        // could otherwise satisfy the rest of the proof.
+        if self.sender.get_left() != self.recipient.get_left() {
+            return false;
+        }
+
+        let p_g = params.generator(FixedGenerators::NoteCommitmentRandomness);
+
+        let e = self.equality_challenge(
+            enc_key_sender,
+            enc_key_recipient,
+            &proof.a_g,
+            &proof.a_sender,
+            &proof.a_recipient,
+        );
+
+        let lhs_g = p_g.mul(proof.z_r, params);
+        let rhs_g = proof.a_g.add(&self.sender.get_left().mul(e, params), params);
+        if lhs_g != rhs_g {
+            return false;
+        }
+
+        let lhs_sender = enc_key_sender.get_point().mul(proof.z_r, params)
+            .add(&p_g.mul(proof.z_m, params), params);
+        let rhs_sender = proof.a_sender.add(&self.sender.get_right().mul(e, params), params);
+        if lhs_sender != rhs_sender {
+            return false;
+        }
+
+        let lhs_recipient = enc_key_recipient.get_point().mul(proof.z_r, params)
+            .add(&p_g.mul(proof.z_m, params), params);
+        let rhs_recipient = proof.a_recipient.add(&self.recipient.get_right().mul(e, params), params);
+
+        lhs_recipient == rhs_recipient
+    }
+}
+
+impl<E: JubjubEngine> CiphertextTrait<E> for MultiCiphertexts<E, Anonymous> {
+    type CA = Anonymous;
+
+    /// Encrypt `amount` to the sender and recipient slots and `0` to every
+    /// decoy slot under a single shared `randomness`. Use
+    /// [`MultiCiphertexts::get_anonymous_set`] or
+    /// [`MultiCiphertexts::write_anonymous_set`] to get the merged set with
+    /// `sender`/`recipient` spliced in at `position`, so every slot's
+    /// ciphertext looks the same to an observer without the position.
+    ///
+    /// The real sender/recipient are placed at the front of the set; use
+    /// [`MultiCiphertexts::encrypt_at`] to place them at a caller-chosen
+    /// position within the decoys.
+    fn encrypt(
+        amount: u32,
+        fee: u32,
+        enc_key_sender: &EncryptionKey<E>,
+        enc_keys: &MultiEncKeys<E, Self::CA>,
+        randomness: &E::Fs,
+        params: &E::Params,
+    ) -> Self {
+        Self::encrypt_at(amount, fee, enc_key_sender, enc_keys, randomness, 0, params)
+    }
+}
+
 impl<E: JubjubEngine> MultiCiphertexts<E, Anonymous> {
     fn new(
         sender: Ciphertext<E>,
         recipient: Ciphertext<E>,
         decoys: Vec<Ciphertext<E>>,
+        position: usize,
         fee: Ciphertext<E>,
     ) -> Self {
         MultiCiphertexts {
             sender,
             recipient,
             decoys: Some(decoys),
+            position: Some(position),
             fee,
             _marker: PhantomData,
         }
     }
+
+    /// Same as [`CiphertextTrait::encrypt`], but lets the caller choose where
+    /// among the decoys the real sender/recipient pair sits, via `position`
+    /// (an index into the decoy key list). The circuit proving correctness of
+    /// the transfer is given this permutation without revealing it on-chain.
+    pub fn encrypt_at(
+        amount: u32,
+        fee: u32,
+        enc_key_sender: &EncryptionKey<E>,
+        enc_keys: &MultiEncKeys<E, Anonymous>,
+        randomness: &E::Fs,
+        position: usize,
+        params: &E::Params,
+    ) -> Self {
+        let p_g = FixedGenerators::NoteCommitmentRandomness;
+        let decoy_keys = enc_keys.get_decoys();
+
+        assert!(
+            position <= decoy_keys.len(),
+            "position must fall within the decoy set"
+        );
+        assert!(
+            decoy_keys.iter().all(|decoy| decoy != enc_key_sender),
+            "decoy keys must be distinct from the sender"
+        );
+
+        let cipher_sender = Ciphertext::encrypt(
+            amount,
+            randomness,
+            enc_key_sender,
+            p_g,
+            params
+        );
+
+        let cipher_recipient = Ciphertext::encrypt(
+            amount,
+            randomness,
+            enc_keys.get_recipient(),
+            p_g,
+            params
+        );
+
+        // Every decoy slot encrypts `0`, under the same randomness as the
+        // real slots, so no slot's ciphertext stands out from the rest.
+        let decoys = decoy_keys
+            .iter()
+            .map(|decoy_key| Ciphertext::encrypt(0, randomness, decoy_key, p_g, params))
+            .collect();
+
+        let cipher_fee = Ciphertext::encrypt(
+            fee,
+            randomness,
+            enc_key_sender,
+            p_g,
+            params
+        );
+
+        MultiCiphertexts::<E, Anonymous>::new(
+            cipher_sender,
+            cipher_recipient,
+            decoys,
+            position,
+            cipher_fee
+        )
+    }
 }
 
 #[derive(Clone)]
@@ -137,6 +413,15 @@ impl<E: JubjubEngine, CA> MultiEncKeys<E, CA> {
     pub fn get_recipient(&self) -> &EncryptionKey<E> {
         &self.recipient
     }
+
+    /// The decoy encryption keys of an anonymity set, or an empty slice for
+    /// `Confidential` transfers which carry no decoys.
+    pub fn get_decoys(&self) -> &[EncryptionKey<E>] {
+        match &self.decoys {
+            Some(decoys) => decoys,
+            None => &[],
+        }
+    }
 }
 
 impl<E: JubjubEngine> MultiEncKeys<E, Confidential> {
@@ -161,3 +446,190 @@ impl<E: JubjubEngine> MultiEncKeys<E, Anonymous> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pairing::bls12_381::{Bls12, Fr};
+    use scrypto::jubjub::JubjubBls12;
+    use rand::{SeedableRng, XorShiftRng};
+
+    #[test]
+    fn equality_proof_round_trips_for_a_genuine_transfer() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([1, 2, 3, 4]);
+
+        let sk_sender = Fr::rand(rng);
+        let sk_recipient = Fr::rand(rng);
+        let p_g = params.generator(FixedGenerators::NoteCommitmentRandomness);
+        let enc_key_sender = EncryptionKey::from_point(p_g.mul(sk_sender, params));
+        let enc_key_recipient = EncryptionKey::from_point(p_g.mul(sk_recipient, params));
+
+        let amount = 10;
+        let fee = 1;
+        let randomness = Fr::rand(rng);
+        let enc_keys = MultiEncKeys::<Bls12, Confidential>::new(enc_key_recipient.clone());
+
+        let multi_ciphertexts = MultiCiphertexts::<Bls12, Confidential>::encrypt(
+            amount,
+            fee,
+            &enc_key_sender,
+            &enc_keys,
+            &randomness,
+            params,
+        );
+
+        let proof = multi_ciphertexts.prove_equality(
+            amount,
+            &randomness,
+            &enc_key_sender,
+            &enc_key_recipient,
+            params,
+            rng,
+        );
+
+        assert!(multi_ciphertexts.verify_equality(&proof, &enc_key_sender, &enc_key_recipient, params));
+    }
+
+    #[test]
+    fn equality_proof_rejects_a_recipient_ciphertext_with_different_randomness() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([5, 6, 7, 8]);
+
+        let sk_sender = Fr::rand(rng);
+        let sk_recipient = Fr::rand(rng);
+        let p_g = params.generator(FixedGenerators::NoteCommitmentRandomness);
+        let enc_key_sender = EncryptionKey::from_point(p_g.mul(sk_sender, params));
+        let enc_key_recipient = EncryptionKey::from_point(p_g.mul(sk_recipient, params));
+
+        let amount = 10;
+        let fee = 1;
+        let randomness = Fr::rand(rng);
+        let enc_keys = MultiEncKeys::<Bls12, Confidential>::new(enc_key_recipient.clone());
+
+        let honest = MultiCiphertexts::<Bls12, Confidential>::encrypt(
+            amount,
+            fee,
+            &enc_key_sender,
+            &enc_keys,
+            &randomness,
+            params,
+        );
+
+        let proof = honest.prove_equality(
+            amount,
+            &randomness,
+            &enc_key_sender,
+            &enc_key_recipient,
+            params,
+            rng,
+        );
+
+        // Swap in a recipient ciphertext encrypting the same amount, but
+        // under fresh randomness: its `c1` no longer matches the sender's,
+        // so it must be rejected even though the linear relation on `c2`
+        // alone would still be satisfiable by some other witness.
+        let forged_randomness = Fr::rand(rng);
+        let forged_recipient = Ciphertext::encrypt(
+            amount,
+            &forged_randomness,
+            &enc_key_recipient,
+            FixedGenerators::NoteCommitmentRandomness,
+            params,
+        );
+        let forged = MultiCiphertexts::<Bls12, Confidential>::new(
+            honest.sender.clone(),
+            forged_recipient,
+            honest.fee.clone(),
+        );
+
+        assert!(!forged.verify_equality(&proof, &enc_key_sender, &enc_key_recipient, params));
+    }
+
+    // Recovers the small plaintext `amount` encrypted in `ciphertext` under
+    // `sk`, by brute-force search over the tiny range these tests use.
+    fn decrypt_u32(ciphertext: &Ciphertext<Bls12>, sk: Fr, params: &JubjubBls12) -> u32 {
+        let p_g = params.generator(FixedGenerators::NoteCommitmentRandomness);
+        let c1 = ciphertext.get_left();
+        let g_m = ciphertext.get_right().add(&c1.mul(sk, params).negate(), params);
+
+        (0..1000)
+            .find(|candidate| p_g.mul(Fr::from_str(&candidate.to_string()).unwrap(), params) == g_m)
+            .expect("amount out of expected test range")
+    }
+
+    #[test]
+    fn encrypt_at_splices_sender_and_recipient_at_position_and_zeroes_decoys() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([11, 12, 13, 14]);
+        let p_g = params.generator(FixedGenerators::NoteCommitmentRandomness);
+
+        let sk_sender = Fr::rand(rng);
+        let sk_recipient = Fr::rand(rng);
+        let sk_decoys: Vec<Fr> = (0..3).map(|_| Fr::rand(rng)).collect();
+
+        let enc_key_sender = EncryptionKey::from_point(p_g.mul(sk_sender, params));
+        let enc_key_recipient = EncryptionKey::from_point(p_g.mul(sk_recipient, params));
+        let enc_key_decoys: Vec<_> = sk_decoys
+            .iter()
+            .map(|sk| EncryptionKey::from_point(p_g.mul(*sk, params)))
+            .collect();
+
+        let amount = 10;
+        let fee = 1;
+        let randomness = Fr::rand(rng);
+        let position = 1;
+        let enc_keys = MultiEncKeys::<Bls12, Anonymous>::new(enc_key_recipient.clone(), enc_key_decoys);
+
+        let multi_ciphertexts = MultiCiphertexts::<Bls12, Anonymous>::encrypt_at(
+            amount,
+            fee,
+            &enc_key_sender,
+            &enc_keys,
+            &randomness,
+            position,
+            params,
+        );
+
+        let anonymous_set = multi_ciphertexts.get_anonymous_set().unwrap();
+        let sks_in_set = [sk_decoys[0], sk_sender, sk_recipient, sk_decoys[1], sk_decoys[2]];
+        assert_eq!(anonymous_set.len(), sks_in_set.len());
+
+        let decrypted: Vec<u32> = anonymous_set
+            .iter()
+            .zip(sks_in_set.iter())
+            .map(|(ciphertext, sk)| decrypt_u32(ciphertext, *sk, params))
+            .collect();
+
+        assert_eq!(decrypted, vec![0, amount, amount, 0, 0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "decoy keys must be distinct from the sender")]
+    fn encrypt_at_panics_when_a_decoy_key_collides_with_the_sender() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([15, 16, 17, 18]);
+        let p_g = params.generator(FixedGenerators::NoteCommitmentRandomness);
+
+        let sk_sender = Fr::rand(rng);
+        let sk_recipient = Fr::rand(rng);
+        let enc_key_sender = EncryptionKey::from_point(p_g.mul(sk_sender, params));
+        let enc_key_recipient = EncryptionKey::from_point(p_g.mul(sk_recipient, params));
+
+        let randomness = Fr::rand(rng);
+        let enc_keys = MultiEncKeys::<Bls12, Anonymous>::new(
+            enc_key_recipient,
+            vec![enc_key_sender.clone()],
+        );
+
+        MultiCiphertexts::<Bls12, Anonymous>::encrypt_at(
+            10,
+            1,
+            &enc_key_sender,
+            &enc_keys,
+            &randomness,
+            0,
+            params,
+        );
+    }
+}
@@ -0,0 +1,330 @@
+//! Dealerless distributed key generation (DKG) for the ElGamal `EncryptionKey`.
+//!
+//! A committee of `n` nodes jointly produces an encryption key whose
+//! decryption secret is `(t+1)`-of-`n` shared, so no single node can ever
+//! decrypt a confidential amount on its own. Each of the `n` nodes acts as
+//! a dealer of a verifiable secret sharing (VSS) scheme built on a
+//! symmetric bivariate polynomial `s(x, y) = sum_{i,j<=t} c_ij x^i y^j`
+//! with `c_ij = c_ji`; summing every dealer's contribution yields the
+//! committee's joint secret with no trusted third party.
+//!
+//! The flow for a single dealer is:
+//! 1. Sample a random `BivarPoly` of degree `t` and publish its
+//!    `BivarCommitment`.
+//! 2. Privately send node `m` the row polynomial `s(m, Y)`.
+//! 3. Node `m` forwards the evaluation `s(m, s)` to node `s`, for every
+//!    other node `s`, after checking its own row against the commitment
+//!    with [`BivarCommitment::verify_row`].
+//! 4. Node `s` checks every received value against the commitment with
+//!    [`BivarCommitment::verify_value`]; once `2t + 1` nodes confirm valid rows, node `s`
+//!    reconstructs its column via Lagrange interpolation and holds a share
+//!    of `s(0, 0)`.
+//!
+//! The committee's master secret is `s(0, 0)`, and the corresponding
+//! `EncryptionKey` is `g^{s(0, 0)}`.
+
+use scrypto::jubjub::{JubjubEngine, FixedGenerators, edwards, PrimeOrder};
+use pairing::Field;
+use rand::Rng;
+
+/// A single-variable polynomial over `E::Fs`, represented by its
+/// coefficients in ascending order of degree.
+#[derive(Clone)]
+pub struct Poly<E: JubjubEngine> {
+    coeffs: Vec<E::Fs>,
+}
+
+impl<E: JubjubEngine> Poly<E> {
+    /// Evaluates the polynomial at `x`.
+    pub fn evaluate(&self, x: E::Fs) -> E::Fs {
+        let mut result = E::Fs::zero();
+        let mut x_pow = E::Fs::one();
+
+        for coeff in &self.coeffs {
+            let mut term = *coeff;
+            term.mul_assign(&x_pow);
+            result.add_assign(&term);
+            x_pow.mul_assign(&x);
+        }
+
+        result
+    }
+}
+
+/// A symmetric bivariate polynomial `s(x, y) = sum_{i,j<=degree} c_ij x^i y^j`
+/// with `c_ij = c_ji`, stored as the upper-triangular coefficients
+/// `c_ij` for `0 <= i <= j <= degree`.
+#[derive(Clone)]
+pub struct BivarPoly<E: JubjubEngine> {
+    degree: usize,
+    coeffs: Vec<E::Fs>,
+}
+
+impl<E: JubjubEngine> BivarPoly<E> {
+    /// Samples a random symmetric bivariate polynomial of the given degree.
+    pub fn random<R: Rng>(degree: usize, rng: &mut R) -> Self {
+        let num_coeffs = (degree + 1) * (degree + 2) / 2;
+        let coeffs = (0..num_coeffs).map(|_| E::Fs::rand(rng)).collect();
+
+        BivarPoly { degree, coeffs }
+    }
+
+    pub fn degree(&self) -> usize {
+        self.degree
+    }
+
+    /// This dealer's contribution to the committee secret, `s(0, 0)`.
+    pub fn secret(&self) -> E::Fs {
+        self.coeffs[self.coeff_index(0, 0)]
+    }
+
+    /// Index of `c_ij` within the triangular `coeffs` storage.
+    fn coeff_index(&self, i: usize, j: usize) -> usize {
+        let (i, j) = if i <= j { (i, j) } else { (j, i) };
+        // Number of entries in rows before `i`, plus the offset into row `i`.
+        i * (2 * self.degree + 3 - i) / 2 + (j - i)
+    }
+
+    /// Evaluates `s(x, y)`.
+    pub fn evaluate(&self, x: E::Fs, y: E::Fs) -> E::Fs {
+        self.row(x).evaluate(y)
+    }
+
+    /// The row polynomial `s(x, Y)`, sent privately to the node at index `x`.
+    pub fn row(&self, x: E::Fs) -> Poly<E> {
+        let mut coeffs = vec![E::Fs::zero(); self.degree + 1];
+        let mut x_pow = E::Fs::one();
+
+        for i in 0..=self.degree {
+            for j in 0..=self.degree {
+                let mut term = self.coeffs[self.coeff_index(i, j)];
+                term.mul_assign(&x_pow);
+                coeffs[j].add_assign(&term);
+            }
+            x_pow.mul_assign(&x);
+        }
+
+        Poly { coeffs }
+    }
+
+    /// Commits to every coefficient as `g^{c_ij}` under the fixed generator,
+    /// so that any row or column evaluation can be checked against the
+    /// commitment without revealing the coefficients themselves.
+    pub fn commitment(&self, params: &E::Params) -> BivarCommitment<E> {
+        let p_g = params.generator(FixedGenerators::NoteCommitmentRandomness);
+
+        let coeffs = self.coeffs.iter()
+            .map(|c| p_g.mul(*c, params))
+            .collect();
+
+        BivarCommitment {
+            degree: self.degree,
+            coeffs,
+        }
+    }
+}
+
+/// A commitment to each coefficient of a [`BivarPoly`] as `g^{c_ij}`, public
+/// so every node can verify the row/column values it receives from the
+/// dealer without learning the coefficients.
+#[derive(Clone)]
+pub struct BivarCommitment<E: JubjubEngine> {
+    degree: usize,
+    coeffs: Vec<edwards::Point<E, PrimeOrder>>,
+}
+
+impl<E: JubjubEngine> BivarCommitment<E> {
+    fn coeff_index(&self, i: usize, j: usize) -> usize {
+        let (i, j) = if i <= j { (i, j) } else { (j, i) };
+        i * (2 * self.degree + 3 - i) / 2 + (j - i)
+    }
+
+    /// Evaluates the commitment at `(x, y)`: `g^{s(x, y)}`, by summing every
+    /// committed coefficient scaled by `x^i y^j`, the group-element
+    /// counterpart of [`BivarPoly::evaluate`].
+    pub fn evaluate(&self, x: E::Fs, y: E::Fs, params: &E::Params) -> edwards::Point<E, PrimeOrder> {
+        let mut x_pow = E::Fs::one();
+        let mut result = edwards::Point::zero();
+
+        for i in 0..=self.degree {
+            let mut y_pow = E::Fs::one();
+            let mut row = edwards::Point::zero();
+
+            for j in 0..=self.degree {
+                row = row.add(&self.coeffs[self.coeff_index(i, j)].mul(y_pow, params), params);
+                y_pow.mul_assign(&y);
+            }
+
+            result = result.add(&row.mul(x_pow, params), params);
+            x_pow.mul_assign(&x);
+        }
+
+        result
+    }
+
+    /// Verifies that `row` is really `s(x_val, Y)`, coefficient by
+    /// coefficient, against the commitment's row at `x_val`. This is the
+    /// check a node performs on the row polynomial the dealer sends it.
+    pub fn verify_row(&self, x_val: E::Fs, row: &Poly<E>, params: &E::Params) -> bool {
+        if row.coeffs.len() != self.degree + 1 {
+            return false;
+        }
+
+        let p_g = params.generator(FixedGenerators::NoteCommitmentRandomness);
+
+        for (j, coeff) in row.coeffs.iter().enumerate() {
+            let mut expected = edwards::Point::zero();
+            let mut x_pow = E::Fs::one();
+
+            for i in 0..=self.degree {
+                expected = expected.add(&self.coeffs[self.coeff_index(i, j)].mul(x_pow, params), params);
+                x_pow.mul_assign(&x_val);
+            }
+
+            if expected != p_g.mul(*coeff, params) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Checks that `value == s(x_val, y_val)` by comparing `g^value`
+    /// against the commitment evaluated at the same point.
+    pub fn verify_value(&self, x_val: E::Fs, y_val: E::Fs, value: E::Fs, params: &E::Params) -> bool {
+        let p_g = params.generator(FixedGenerators::NoteCommitmentRandomness);
+        p_g.mul(value, params) == self.evaluate(x_val, y_val, params)
+    }
+}
+
+/// Reconstructs a polynomial's value at `0` from `t + 1` of its evaluations,
+/// via Lagrange interpolation at `x = 0`. `samples` are `(x_i, s(x_i))`
+/// pairs; the node indices `x_i` must be distinct and nonzero.
+pub fn lagrange_interpolate_at_zero<E: JubjubEngine>(samples: &[(E::Fs, E::Fs)]) -> E::Fs {
+    let mut result = E::Fs::zero();
+
+    for (i, &(x_i, y_i)) in samples.iter().enumerate() {
+        let mut numerator = E::Fs::one();
+        let mut denominator = E::Fs::one();
+
+        for (k, &(x_k, _)) in samples.iter().enumerate() {
+            if k == i {
+                continue;
+            }
+
+            // Numerator picks up `(0 - x_k) = -x_k`; denominator, `(x_i - x_k)`.
+            let mut neg_x_k = x_k;
+            neg_x_k.negate();
+            numerator.mul_assign(&neg_x_k);
+
+            let mut diff = x_i;
+            diff.sub_assign(&x_k);
+            denominator.mul_assign(&diff);
+        }
+
+        let mut lambda_i = numerator;
+        lambda_i.mul_assign(&denominator.inverse().expect("sample node indices must be distinct"));
+
+        let mut term = y_i;
+        term.mul_assign(&lambda_i);
+        result.add_assign(&term);
+    }
+
+    result
+}
+
+/// Derives the group-element key share `g^{secret}` corresponding to a
+/// node's reconstructed secret share (or, summed across every dealer, the
+/// committee's master `EncryptionKey` point `g^{s(0, 0)}`).
+pub fn derive_key_share<E: JubjubEngine>(secret: E::Fs, params: &E::Params) -> edwards::Point<E, PrimeOrder> {
+    let p_g = params.generator(FixedGenerators::NoteCommitmentRandomness);
+    p_g.mul(secret, params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pairing::bls12_381::{Bls12, Fr};
+    use scrypto::jubjub::JubjubBls12;
+    use rand::{SeedableRng, XorShiftRng};
+
+    #[test]
+    fn row_and_value_verify_against_the_commitment() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([1, 2, 3, 4]);
+
+        let degree = 2;
+        let poly = BivarPoly::<Bls12>::random(degree, rng);
+        let commitment = poly.commitment(params);
+
+        let x_val = Fr::from_str("5").unwrap();
+        let y_val = Fr::from_str("7").unwrap();
+
+        let row = poly.row(x_val);
+        assert!(commitment.verify_row(x_val, &row, params));
+
+        let value = row.evaluate(y_val);
+        assert_eq!(value, poly.evaluate(x_val, y_val));
+        assert!(commitment.verify_value(x_val, y_val, value, params));
+    }
+
+    #[test]
+    fn tampered_row_fails_verification() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([5, 6, 7, 8]);
+
+        let poly = BivarPoly::<Bls12>::random(1, rng);
+        let commitment = poly.commitment(params);
+
+        let x_val = Fr::from_str("3").unwrap();
+        let mut row = poly.row(x_val);
+        row.coeffs[0].add_assign(&Fr::one());
+
+        assert!(!commitment.verify_row(x_val, &row, params));
+    }
+
+    #[test]
+    fn tampered_value_fails_verification() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([9, 10, 11, 12]);
+
+        let poly = BivarPoly::<Bls12>::random(1, rng);
+        let commitment = poly.commitment(params);
+
+        let x_val = Fr::from_str("3").unwrap();
+        let y_val = Fr::from_str("4").unwrap();
+        let mut value = poly.evaluate(x_val, y_val);
+        value.add_assign(&Fr::one());
+
+        assert!(!commitment.verify_value(x_val, y_val, value, params));
+    }
+
+    #[test]
+    fn node_reconstructs_its_column_from_forwarded_evaluations() {
+        let rng = &mut XorShiftRng::from_seed([13, 14, 15, 16]);
+
+        // Degree-1 polynomial: node `y_val` needs only 2 forwarded
+        // evaluations `s(1, y_val)`, `s(2, y_val)` to reconstruct its own
+        // share `s(0, y_val)` via Lagrange interpolation at x = 0.
+        let poly = BivarPoly::<Bls12>::random(1, rng);
+
+        let x1 = Fr::from_str("1").unwrap();
+        let x2 = Fr::from_str("2").unwrap();
+        let y_val = Fr::from_str("9").unwrap();
+
+        let share1 = poly.evaluate(x1, y_val);
+        let share2 = poly.evaluate(x2, y_val);
+
+        let reconstructed = lagrange_interpolate_at_zero::<Bls12>(&[(x1, share1), (x2, share2)]);
+
+        assert_eq!(reconstructed, poly.evaluate(Fr::zero(), y_val));
+    }
+
+    #[test]
+    fn master_secret_is_the_shared_secret_at_the_origin() {
+        let rng = &mut XorShiftRng::from_seed([17, 18, 19, 20]);
+        let poly = BivarPoly::<Bls12>::random(1, rng);
+
+        assert_eq!(poly.secret(), poly.evaluate(Fr::zero(), Fr::zero()));
+    }
+}
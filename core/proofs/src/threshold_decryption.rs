@@ -0,0 +1,341 @@
+//! Threshold decryption of `elgamal::Ciphertext`, built on top of the
+//! `(t+1)`-of-`n` shared decryption secret produced by [`crate::keygen`].
+//!
+//! Each of the `n` key-share holders publishes a decryption share
+//! `d_i = c1^{x_i}` together with a Chaum–Pedersen proof that `d_i` was
+//! computed with the same `x_i` committed to in its public verification key
+//! `pk_i = g^{x_i}`. Any `t + 1` valid shares can then be combined via
+//! Lagrange interpolation in the exponent to recover `c1^x = g^{r*x}` and,
+//! from there, the plaintext; the scalar `x` itself is never reconstructed
+//! or held by any single party, only linear combinations of group elements.
+
+use scrypto::jubjub::{JubjubEngine, FixedGenerators, edwards, PrimeOrder};
+use crate::elgamal::Ciphertext;
+use pairing::Field;
+use rand::Rng;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+/// A Chaum–Pedersen proof of equality of discrete logs: that the same
+/// scalar `x_i` satisfies both `pk_i = g^{x_i}` and `d_i = c1^{x_i}`.
+#[derive(Clone)]
+pub struct EqualityProof<E: JubjubEngine> {
+    /// `g^k` for the prover's random nonce `k`.
+    a: edwards::Point<E, PrimeOrder>,
+    /// `c1^k` for the same nonce `k`.
+    b: edwards::Point<E, PrimeOrder>,
+    /// `z = k + e * x_i`, the response to the Fiat-Shamir challenge `e`.
+    z: E::Fs,
+}
+
+/// One key-share holder's contribution towards decrypting a `Ciphertext`.
+#[derive(Clone)]
+pub struct DecryptionShare<E: JubjubEngine> {
+    /// The holder's node index `i`, used as the `x` coordinate when
+    /// Lagrange-combining shares.
+    index: E::Fs,
+    /// `d_i = c1^{x_i}`.
+    share: edwards::Point<E, PrimeOrder>,
+    proof: EqualityProof<E>,
+}
+
+impl<E: JubjubEngine> DecryptionShare<E> {
+    pub fn share(&self) -> &edwards::Point<E, PrimeOrder> {
+        &self.share
+    }
+
+    pub fn index(&self) -> E::Fs {
+        self.index
+    }
+}
+
+/// Fiat–Shamir challenge `e` for the Chaum–Pedersen proof, derived from
+/// every public value the verifier also has on hand.
+fn equality_challenge<E: JubjubEngine>(
+    pk_i: &edwards::Point<E, PrimeOrder>,
+    c1: &edwards::Point<E, PrimeOrder>,
+    d_i: &edwards::Point<E, PrimeOrder>,
+    a: &edwards::Point<E, PrimeOrder>,
+    b: &edwards::Point<E, PrimeOrder>,
+    params: &E::Params,
+) -> E::Fs {
+    let mut repr = Vec::new();
+    for point in &[pk_i, c1, d_i, a, b] {
+        point.write(&mut repr).expect("writing to a Vec never fails");
+    }
+
+    E::Fs::to_uniform(&repr).expect("hash output is large enough to reduce into Fs")
+}
+
+/// Computes this node's decryption share `d_i = c1^{x_i}` for `ciphertext`,
+/// along with a Chaum–Pedersen proof that `x_i` is the same scalar behind
+/// the node's public verification key `pk_i = g^{x_i}`.
+pub fn decryption_share<E: JubjubEngine, R: Rng>(
+    x_i: E::Fs,
+    index: E::Fs,
+    ciphertext: &Ciphertext<E>,
+    params: &E::Params,
+    rng: &mut R,
+) -> DecryptionShare<E> {
+    let p_g = params.generator(FixedGenerators::NoteCommitmentRandomness);
+    let c1 = ciphertext.get_left();
+
+    let d_i = c1.mul(x_i, params);
+
+    let k = E::Fs::rand(rng);
+    let a = p_g.mul(k, params);
+    let b = c1.mul(k, params);
+
+    let pk_i = p_g.mul(x_i, params);
+    let e = equality_challenge::<E>(&pk_i, c1, &d_i, &a, &b, params);
+
+    let mut z = e;
+    z.mul_assign(&x_i);
+    z.add_assign(&k);
+
+    DecryptionShare {
+        index,
+        share: d_i,
+        proof: EqualityProof { a, b, z },
+    }
+}
+
+/// Verifies that `share` was honestly computed from the secret behind
+/// `pk_i`, without learning that secret: checks `g^z = a * pk_i^e` and
+/// `c1^z = b * d_i^e` for the Fiat–Shamir challenge `e`.
+pub fn verify_share<E: JubjubEngine>(
+    share: &DecryptionShare<E>,
+    pk_i: &edwards::Point<E, PrimeOrder>,
+    ciphertext: &Ciphertext<E>,
+    params: &E::Params,
+) -> bool {
+    let p_g = params.generator(FixedGenerators::NoteCommitmentRandomness);
+    let c1 = ciphertext.get_left();
+    let EqualityProof { a, b, z } = &share.proof;
+
+    let e = equality_challenge::<E>(pk_i, c1, &share.share, a, b, params);
+
+    let lhs_g = p_g.mul(*z, params);
+    let rhs_g = a.add(&pk_i.mul(e, params), params);
+
+    let lhs_c1 = c1.mul(*z, params);
+    let rhs_c1 = b.add(&share.share.mul(e, params), params);
+
+    lhs_g == rhs_g && lhs_c1 == rhs_c1
+}
+
+/// Lagrange coefficient `lambda_i = prod_{k != i} (0 - x_k) / (x_i - x_k)`,
+/// for combining shares at `x = 0`.
+fn lagrange_coefficient<E: JubjubEngine>(index: E::Fs, other_indices: &[E::Fs]) -> E::Fs {
+    let mut numerator = E::Fs::one();
+    let mut denominator = E::Fs::one();
+
+    for &x_k in other_indices {
+        if x_k == index {
+            continue;
+        }
+
+        let mut neg_x_k = x_k;
+        neg_x_k.negate();
+        numerator.mul_assign(&neg_x_k);
+
+        let mut diff = index;
+        diff.sub_assign(&x_k);
+        denominator.mul_assign(&diff);
+    }
+
+    let mut lambda = numerator;
+    lambda.mul_assign(&denominator.inverse().expect("share indices must be distinct"));
+    lambda
+}
+
+/// Combines `t + 1` valid decryption shares into the confidential `amount`
+/// encrypted in `ciphertext`. Shares are combined as `c1^x = prod d_i^{lambda_i}`
+/// via Lagrange interpolation in the exponent; the recovered `g^m` is then
+/// searched for over the bounded range `[0, 2^32)` using the same
+/// baby-step/giant-step discrete-log approach used elsewhere to recover a
+/// `u32` amount from its ElGamal encryption.
+pub fn combine_shares<E: JubjubEngine>(
+    shares: &[DecryptionShare<E>],
+    ciphertext: &Ciphertext<E>,
+    params: &E::Params,
+) -> Option<u32> {
+    let indices: Vec<E::Fs> = shares.iter().map(|s| s.index).collect();
+
+    let mut c1_x = edwards::Point::zero();
+    for share in shares {
+        let lambda_i = lagrange_coefficient::<E>(share.index, &indices);
+        c1_x = c1_x.add(&share.share.mul(lambda_i, params), params);
+    }
+
+    // g^m = c2 / c1^x
+    let g_m = ciphertext.get_right().add(&c1_x.negate(), params);
+
+    find_discrete_log::<E>(&g_m, params)
+}
+
+/// Baby-step/giant-step search for `m` in `g^m = point`, bounded to
+/// `m in [0, 2^32)`, mirroring the bounded discrete-log recovery used
+/// elsewhere to decode a `u32` amount from its ElGamal encryption.
+fn find_discrete_log<E: JubjubEngine>(
+    point: &edwards::Point<E, PrimeOrder>,
+    params: &E::Params,
+) -> Option<u32> {
+    let p_g = params.generator(FixedGenerators::NoteCommitmentRandomness);
+
+    // `m = step * giant + baby`, with `giant, baby` in `[0, step)` and
+    // `step = ceil(sqrt(2^32))`.
+    let step: u64 = 1 << 16;
+
+    // Points aren't `Hash`, so key the baby-step table by each point's
+    // canonical byte encoding instead; this keeps the lookup below O(1)
+    // rather than an O(step) scan, which is the entire point of doing
+    // baby-step/giant-step instead of a linear search over `2^32`.
+    let mut baby_steps = HashMap::with_capacity(step as usize);
+    let mut acc = edwards::Point::zero();
+    for baby in 0..step {
+        let mut repr = Vec::new();
+        acc.write(&mut repr).expect("writing to a Vec never fails");
+        baby_steps.insert(repr, baby as u32);
+        acc = acc.add(&p_g, params);
+    }
+
+    let giant_stride = p_g.mul(E::Fs::from_str(&step.to_string())
+        .expect("step fits in the scalar field"), params)
+        .negate();
+
+    let mut current = point.clone();
+    for giant in 0..step {
+        let mut repr = Vec::new();
+        current.write(&mut repr).expect("writing to a Vec never fails");
+
+        if let Some(&baby) = baby_steps.get(&repr) {
+            let m = (giant as u64) * step + baby as u64;
+            return u32::try_from(m).ok();
+        }
+        current = current.add(&giant_stride, params);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EncryptionKey;
+    use pairing::bls12_381::{Bls12, Fr};
+    use scrypto::jubjub::JubjubBls12;
+    use rand::{SeedableRng, XorShiftRng};
+
+    #[test]
+    fn discrete_log_recovers_a_bounded_amount() {
+        let params = &JubjubBls12::new();
+        let p_g = params.generator(FixedGenerators::NoteCommitmentRandomness);
+
+        let amount: u32 = 123_456_789;
+        let amount_fs = Fr::from_str(&amount.to_string()).unwrap();
+        let point = p_g.mul(amount_fs, params);
+
+        assert_eq!(find_discrete_log::<Bls12>(&point, params), Some(amount));
+    }
+
+    #[test]
+    fn lagrange_coefficients_recombine_a_shared_secret() {
+        // f(y) = secret + a1*y; any 2 of 3 evaluations reconstruct f(0).
+        let rng = &mut XorShiftRng::from_seed([21, 22, 23, 24]);
+        let secret = Fr::rand(rng);
+        let a1 = Fr::rand(rng);
+
+        let f = |y: u64| {
+            let y_fs = Fr::from_str(&y.to_string()).unwrap();
+            let mut v = a1;
+            v.mul_assign(&y_fs);
+            v.add_assign(&secret);
+            v
+        };
+
+        let indices = vec![Fr::from_str("1").unwrap(), Fr::from_str("2").unwrap()];
+        let shares = vec![f(1), f(2)];
+
+        let mut recombined = Fr::zero();
+        for (index, share) in indices.iter().zip(shares.iter()) {
+            let lambda = lagrange_coefficient::<Bls12>(*index, &indices);
+            let mut term = *share;
+            term.mul_assign(&lambda);
+            recombined.add_assign(&term);
+        }
+
+        assert_eq!(recombined, secret);
+    }
+
+    #[test]
+    fn shares_round_trip_to_the_original_amount() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([25, 26, 27, 28]);
+
+        // Shamir share the decryption secret with a degree-1 polynomial:
+        // any 2 of 3 nodes can decrypt.
+        let secret = Fr::rand(rng);
+        let a1 = Fr::rand(rng);
+        let x_at = |i: u64| {
+            let i_fs = Fr::from_str(&i.to_string()).unwrap();
+            let mut v = a1;
+            v.mul_assign(&i_fs);
+            v.add_assign(&secret);
+            v
+        };
+
+        let index1 = Fr::from_str("1").unwrap();
+        let index2 = Fr::from_str("2").unwrap();
+        let x1 = x_at(1);
+        let x2 = x_at(2);
+
+        let p_g = params.generator(FixedGenerators::NoteCommitmentRandomness);
+        let pk1 = p_g.mul(x1, params);
+        let pk2 = p_g.mul(x2, params);
+
+        let enc_key = EncryptionKey::from_point(p_g.mul(secret, params));
+        let amount: u32 = 42;
+        let randomness = Fr::rand(rng);
+        let ciphertext = Ciphertext::encrypt(
+            amount,
+            &randomness,
+            &enc_key,
+            FixedGenerators::NoteCommitmentRandomness,
+            params,
+        );
+
+        let share1 = decryption_share::<Bls12, _>(x1, index1, &ciphertext, params, rng);
+        let share2 = decryption_share::<Bls12, _>(x2, index2, &ciphertext, params, rng);
+
+        assert!(verify_share(&share1, &pk1, &ciphertext, params));
+        assert!(verify_share(&share2, &pk2, &ciphertext, params));
+
+        let recovered = combine_shares(&[share1, share2], &ciphertext, params);
+        assert_eq!(recovered, Some(amount));
+    }
+
+    #[test]
+    fn share_with_wrong_key_fails_verification() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([29, 30, 31, 32]);
+
+        let x_i = Fr::rand(rng);
+        let wrong_pk = params.generator(FixedGenerators::NoteCommitmentRandomness).mul(Fr::rand(rng), params);
+
+        let enc_key = EncryptionKey::from_point(
+            params.generator(FixedGenerators::NoteCommitmentRandomness).mul(x_i, params),
+        );
+        let ciphertext = Ciphertext::encrypt(
+            7,
+            &Fr::rand(rng),
+            &enc_key,
+            FixedGenerators::NoteCommitmentRandomness,
+            params,
+        );
+
+        let share = decryption_share::<Bls12, _>(x_i, Fr::from_str("1").unwrap(), &ciphertext, params, rng);
+
+        assert!(!verify_share(&share, &wrong_pk, &ciphertext, params));
+    }
+}
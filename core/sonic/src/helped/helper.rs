@@ -13,8 +13,9 @@
 //! This submodule contains the `Batch` abstraction for creating a
 //! context for batch verification.
 
-use pairing::{Engine, CurveAffine, CurveProjective};
+use pairing::{Engine, CurveAffine, CurveProjective, Field};
 use crate::srs::SRS;
+use crate::util::multiexp;
 
 pub struct Batch<E: Engine> {
     /// Context of openings of polynomial commitment
@@ -72,15 +73,180 @@ impl<E: Engine> Batch<E> {
         self.neg_h.push((comm, random));
     }
 
+    /// Accumulate one polynomial commitment opening `(W, r, z)` into the
+    /// batch, folded in by the challenge `random` (`s` in the protocol
+    /// description). `s*W` enters `alpha_x`, and `-s*z*W` enters `alpha`,
+    /// matching the equation `W^{x - z} = g^v` that a single opening must
+    /// satisfy; the claimed value `v` is folded in separately via
+    /// `add_opening_value`, and `check_all` adds its `-v*g` term to `alpha`.
     pub fn add_opening(&mut self, opening: E::G1Affine, mut random: E::Fr, point: E::Fr) {
         self.alpha_x.push((opening, random));
 
+        random.mul_assign(&point);
+        random.negate();
+        self.alpha.push((opening, random));
+    }
 
+    /// Fold the claimed evaluation `v` of an opening into the batch's
+    /// running `value`, scaled by the same challenge `random` used for that
+    /// opening so the two stay linked in the random linear combination.
+    pub fn add_opening_value(&mut self, random: E::Fr, mut value: E::Fr) {
+        value.mul_assign(&random);
+        self.value.add_assign(&value);
     }
 
+    /// Reduce every accumulator to a single `G1` point via multiexponentiation
+    /// and check the whole batch with one product of pairings. Because the
+    /// per-opening challenges are unpredictable to the prover, this random
+    /// linear combination of the individual opening equations holds with
+    /// overwhelming probability iff every individual equation holds.
     pub fn check_all(mut self) -> bool {
-        unimplemented!();
+        let mut neg_value = self.value;
+        neg_value.negate();
+        self.alpha.push((self.g, neg_value));
+
+        let alpha_x = multiexp(&self.alpha_x).into_affine().prepare();
+        let alpha = multiexp(&self.alpha).into_affine().prepare();
+        let neg_h = multiexp(&self.neg_h).into_affine().prepare();
+        let neg_x_n_minus_d = multiexp(&self.neg_x_n_minus_d).into_affine().prepare();
+
+        E::final_exponentiation(&E::miller_loop(&[
+            (&alpha_x, &self.alpha_x_precomp),
+            (&alpha, &self.alpha_precomp),
+            (&neg_h, &self.neg_h_precomp),
+            (&neg_x_n_minus_d, &self.neg_x_n_minus_d_precomp),
+        ])).map(|v| v == E::Fqk::one()).unwrap_or(false)
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::{SeedableRng, XorShiftRng, Rng};
+
+    // Builds a `Batch` directly from its accumulators rather than via
+    // `Batch::new`, so the test doesn't need a real `SRS`. `neg_h` and
+    // `neg_x_n_minus_d` are left empty (no `add_comm` calls, and nothing
+    // pushes to `neg_x_n_minus_d`), so their precomputed G2 values never enter the pairing
+    // product and can be anything.
+    fn empty_batch(alpha: Fr, x: Fr) -> Batch<Bls12> {
+        let g = <Bls12 as Engine>::G1::one().into_affine();
+        let h = <Bls12 as Engine>::G2::one();
+
+        let mut h_alpha_x = h;
+        h_alpha_x.mul_assign(x);
+        h_alpha_x.mul_assign(alpha);
+
+        let mut h_alpha = h;
+        h_alpha.mul_assign(alpha);
+
+        Batch {
+            alpha_x: vec![],
+            alpha_x_precomp: h_alpha_x.into_affine().prepare(),
+
+            alpha: vec![],
+            alpha_precomp: h_alpha.into_affine().prepare(),
+
+            neg_h: vec![],
+            neg_h_precomp: h.into_affine().prepare(),
+
+            neg_x_n_minus_d: vec![],
+            neg_x_n_minus_d_precomp: h.into_affine().prepare(),
+
+            value: Fr::zero(),
+            g,
+        }
+    }
+
+    // `W = g^{v/(x-z)}` is the KZG-style opening that `check_all`'s
+    // pairing equation accepts for "the committed polynomial evaluates
+    // to `v` at `z`" under trapdoor `x`.
+    fn genuine_opening(x: Fr, z: Fr, v: Fr) -> <Bls12 as Engine>::G1Affine {
+        let g = <Bls12 as Engine>::G1::one().into_affine();
+
+        let mut x_minus_z = x;
+        x_minus_z.sub_assign(&z);
+
+        let mut exponent = v;
+        exponent.mul_assign(&x_minus_z.inverse().unwrap());
+
+        g.mul(exponent).into_affine()
+    }
+
+    #[test]
+    fn check_all_accepts_a_genuine_opening() {
+        let rng = &mut XorShiftRng::from_seed([1, 2, 3, 4]);
+        let alpha = Fr::rand(rng);
+        let x = Fr::rand(rng);
+        let z = Fr::rand(rng);
+        let v = Fr::rand(rng);
+
+        let w = genuine_opening(x, z, v);
+
+        let mut batch = empty_batch(alpha, x);
+        batch.add_opening(w, Fr::one(), z);
+        batch.add_opening_value(Fr::one(), v);
+
+        assert!(batch.check_all());
+    }
+
+    #[test]
+    fn check_all_rejects_a_tampered_claimed_value() {
+        let rng = &mut XorShiftRng::from_seed([1, 2, 3, 4]);
+        let alpha = Fr::rand(rng);
+        let x = Fr::rand(rng);
+        let z = Fr::rand(rng);
+        let v = Fr::rand(rng);
+
+        let w = genuine_opening(x, z, v);
+
+        let mut wrong_v = v;
+        wrong_v.add_assign(&Fr::one());
+
+        let mut batch = empty_batch(alpha, x);
+        batch.add_opening(w, Fr::one(), z);
+        batch.add_opening_value(Fr::one(), wrong_v);
+
+        assert!(!batch.check_all());
+    }
+
+    #[test]
+    fn check_all_rejects_a_tampered_point() {
+        let rng = &mut XorShiftRng::from_seed([1, 2, 3, 4]);
+        let alpha = Fr::rand(rng);
+        let x = Fr::rand(rng);
+        let z = Fr::rand(rng);
+        let v = Fr::rand(rng);
+
+        let w = genuine_opening(x, z, v);
+
+        let mut wrong_z = z;
+        wrong_z.add_assign(&Fr::one());
+
+        let mut batch = empty_batch(alpha, x);
+        batch.add_opening(w, Fr::one(), wrong_z);
+        batch.add_opening_value(Fr::one(), v);
+
+        assert!(!batch.check_all());
+    }
+
+    #[test]
+    fn check_all_rejects_a_tampered_opening() {
+        let rng = &mut XorShiftRng::from_seed([1, 2, 3, 4]);
+        let alpha = Fr::rand(rng);
+        let x = Fr::rand(rng);
+        let z = Fr::rand(rng);
+        let v = Fr::rand(rng);
+
+        let genuine = genuine_opening(x, z, v);
+        let wrong_w = genuine.mul(Fr::rand(rng)).into_affine();
+
+        let mut batch = empty_batch(alpha, x);
+        batch.add_opening(wrong_w, Fr::one(), z);
+        batch.add_opening_value(Fr::one(), v);
+
+        assert!(!batch.check_all());
+    }
+}
 